@@ -1,12 +1,133 @@
+// This binary is a library of search/automaton algorithms exercised by its
+// test suite rather than by `main`, which is just a smoke-test entry
+// point; allow the pieces only `#[cfg(test)]` code calls into.
+#![allow(dead_code)]
+
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use memmap2::Mmap;
+
+/// How often a worker in `find_path_parallel` reports a `SearchProgress`
+/// snapshot, so callers don't flood a slow progress consumer.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
 
 type State = usize;
 
+/// A semiring of derivation weights, generalizing the crate's original
+/// tropical (min-plus) `usize` weights so the same best-derivation engine
+/// can be instantiated for other cost algebras: Viterbi (max-product
+/// probability), a lexicographic/Pareto tuple, and so on.
+///
+/// `add` is the "choose between alternatives" operator (`min`, for
+/// tropical); `mul` is the "combine weights along a single derivation"
+/// operator (`+`, for tropical).
+///
+/// `find_path`/`find_k_best`/`find_path_parallel` are only guaranteed to
+/// return an optimal tree for *superior* (idempotent, monotone)
+/// semirings: `add` must be idempotent (`a.add(&a) == a`) so it induces
+/// the natural partial order used below (`preferred_over`), and `mul`
+/// must be monotone with respect to that order in both arguments, so
+/// extending a derivation can never make it "better". An arbitrary user
+/// `Semiring` that violates this can make the search return a suboptimal
+/// tree.
+trait Semiring: Clone + PartialEq {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+
+    /// The natural order induced by `add`: true iff choosing between
+    /// `self` and `other` keeps `self` (`self.add(other) == *self`).
+    fn preferred_over(&self, other: &Self) -> bool {
+        self.add(other) == *self
+    }
+
+    /// True iff `self` is preferred over `other` and they aren't tied.
+    fn strictly_better(&self, other: &Self) -> bool {
+        self.preferred_over(other) && !other.preferred_over(self)
+    }
+}
+
+/// The tropical (min-plus) semiring: `add` is `min`, `mul` is `+`. This
+/// is the crate's original `usize`-weight behavior.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Tropical(usize);
+
+impl Semiring for Tropical {
+    fn zero() -> Self {
+        Tropical(usize::MAX)
+    }
+
+    fn one() -> Self {
+        Tropical(0)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Tropical(self.0.min(other.0))
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Tropical(self.0.saturating_add(other.0))
+    }
+}
+
+/// A type that can be encoded to, and decoded from, a compact byte
+/// representation for `TreeAutomation::save`/`load`. Implemented for the
+/// symbol type `S` and its `S::Weight`.
+trait Serializable: Sized {
+    /// Appends this value's encoding to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+    /// Decodes a value from the front of `buf`, returning it along with
+    /// the number of bytes consumed. Fails with `io::ErrorKind::InvalidData`
+    /// rather than panicking when `buf` is too short to hold an encoded
+    /// value, so a truncated or corrupt file surfaces as an `Err` instead
+    /// of a panic.
+    fn decode(buf: &[u8]) -> io::Result<(Self, usize)>;
+}
+
+/// Builds the `io::Error` a truncated or corrupt encoding decodes to.
+fn truncated(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("truncated {what}"))
+}
+
+impl Serializable for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(*self as u64).to_le_bytes());
+    }
+
+    fn decode(buf: &[u8]) -> io::Result<(Self, usize)> {
+        let bytes: [u8; 8] = buf
+            .get(..8)
+            .ok_or_else(|| truncated("usize"))?
+            .try_into()
+            .unwrap();
+        Ok((u64::from_le_bytes(bytes) as usize, 8))
+    }
+}
+
+impl Serializable for Tropical {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+
+    fn decode(buf: &[u8]) -> io::Result<(Self, usize)> {
+        let (value, consumed) = usize::decode(buf)?;
+        Ok((Tropical(value), consumed))
+    }
+}
+
 trait SymbolTrait: Eq + Clone {
-    fn weight(&self) -> usize;
+    type Weight: Semiring;
+    fn weight(&self) -> Self::Weight;
 }
 
 enum StatePair {
@@ -14,10 +135,37 @@ enum StatePair {
     Pair(State, State),
 }
 
+/// A lower-bound estimate of the remaining weight to reach `final_state`,
+/// used to steer `find_path` toward the goal instead of exploring
+/// uniformly by accumulated weight (Dijkstra) alone.
+///
+/// An implementation must be admissible: `estimate(state)` must never
+/// overestimate, under `W`'s natural order, the true minimal weight
+/// remaining from `state` to `final_state`. Overestimating breaks the
+/// optimality guarantee of `find_path`.
+trait Heuristic<W: Semiring> {
+    fn estimate(&self, state: State) -> W;
+}
+
+/// The trivial admissible heuristic: always estimates `W::one()`, the
+/// `mul` identity, so it never changes a node's priority. Plugging this
+/// in recovers the original uniform-cost search.
+struct ZeroHeuristic;
+
+impl<W: Semiring> Heuristic<W> for ZeroHeuristic {
+    fn estimate(&self, _state: State) -> W {
+        W::one()
+    }
+}
+
 struct Transition<S: SymbolTrait> {
     items: HashMap<State, Vec<(Option<State>, S, State)>>,
 }
 
+/// `Transition::incoming`'s result: for each state, the edges that produce
+/// it, as (source state, optional pair sibling, symbol) triples.
+type IncomingEdges<S> = HashMap<State, Vec<(State, Option<State>, S)>>;
+
 impl<S: SymbolTrait> Transition<S> {
     fn new() -> Self {
         Transition {
@@ -29,21 +177,21 @@ impl<S: SymbolTrait> Transition<S> {
             StatePair::Single(state) => {
                 // Add transition for a single state
                 self.items
-                    .entry(state.clone())
-                    .or_insert_with(Vec::new)
+                    .entry(state)
+                    .or_default()
                     .push((None, symbol, end));
             }
             StatePair::Pair(state1, state2) => {
                 // Add transitions for a pair of states
                 self.items
-                    .entry(state1.clone())
-                    .or_insert_with(Vec::new)
-                    .push((Some(state2.clone()), symbol.clone(), end.clone()));
+                    .entry(state1)
+                    .or_default()
+                    .push((Some(state2), symbol.clone(), end));
 
                 self.items
-                    .entry(state1.clone())
-                    .or_insert_with(Vec::new)
-                    .push((Some(state1.clone()), symbol, end));
+                    .entry(state1)
+                    .or_default()
+                    .push((Some(state1), symbol, end));
             }
         }
     }
@@ -51,7 +199,7 @@ impl<S: SymbolTrait> Transition<S> {
     fn transition_list(
         &self,
         from: State,
-        used_node: &HashMap<State, Rc<TreeNode<S>>>,
+        used_node: &HashMap<State, Arc<TreeNode<S>>>,
     ) -> Vec<(Option<State>, S, State)> {
         self.items
             .get(&from)
@@ -64,40 +212,133 @@ impl<S: SymbolTrait> Transition<S> {
             .cloned() // Clone each item (needed since we are collecting into a Vec)
             .collect() // Collect the results into a Vec
     }
+
+    /// All transitions grouped by the state they produce, as
+    /// `(from, near, symbol)`. `transition_list` walks transitions in the
+    /// "what does this state feed" direction; `find_k_best` needs the
+    /// opposite "what feeds this state" direction, so build it once here.
+    fn incoming(&self) -> HashMap<State, Vec<(State, Option<State>, S)>> {
+        let mut result: HashMap<State, Vec<(State, Option<State>, S)>> = HashMap::new();
+        for (&from, entries) in self.items.iter() {
+            for (near, symbol, to) in entries.iter() {
+                result
+                    .entry(*to)
+                    .or_default()
+                    .push((from, *near, symbol.clone()));
+            }
+        }
+        result
+    }
 }
 
 struct TreeNode<S: SymbolTrait> {
     state: State,
-    first_child: Option<Rc<TreeNode<S>>>,
-    second_child: Option<Rc<TreeNode<S>>>,
+    first_child: Option<Arc<TreeNode<S>>>,
+    second_child: Option<Arc<TreeNode<S>>>,
     symbol: Option<S>,
-    weight: usize,
+    weight: S::Weight,
+    // g (`weight`) combined, via the semiring's `mul`, with the heuristic
+    // estimate h for the state this node occupies. This is what the heap
+    // orders by; `weight` alone is kept on the node because it is the
+    // true accumulated cost needed for relaxation and tree reconstruction.
+    priority: S::Weight,
 }
 
 impl<S: SymbolTrait> PartialOrd for TreeNode<S> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.weight.partial_cmp(&self.weight) // Reverse the comparison
+        Some(self.cmp(other))
     }
 }
 
 impl<S: SymbolTrait> Ord for TreeNode<S> {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.weight.cmp(&self.weight) // Reverse the comparison
+        // Reversed, as before, so a `BinaryHeap` (a max-heap) pops the
+        // most-preferred node first; "most preferred" is now the
+        // semiring's natural order instead of raw `usize` comparison.
+        if self.priority.strictly_better(&other.priority) {
+            Ordering::Greater
+        } else if other.priority.strictly_better(&self.priority) {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }
     }
 }
 
 impl<S: SymbolTrait> PartialEq for TreeNode<S> {
     fn eq(&self, other: &Self) -> bool {
-        self.weight == other.weight
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl<S: SymbolTrait> Eq for TreeNode<S> {}
 
+/// A concrete ranked tree to test for acceptance, as opposed to the trees
+/// `find_path`/`find_k_best` generate. Mirrors the transition shapes in
+/// `Transition`: a leaf seeds from `start_state`, a unary node applies a
+/// `Single` transition to its child, and a binary node applies a `Pair`
+/// transition to its two children.
+enum InputTree<S: SymbolTrait> {
+    Leaf,
+    Unary(S, Box<InputTree<S>>),
+    Binary(S, Box<InputTree<S>>, Box<InputTree<S>>),
+}
+
+/// A snapshot of `find_path_parallel`'s progress, streamed over its
+/// `progress` channel roughly every `PROGRESS_INTERVAL` so callers can
+/// show throughput and the current best weight found so far.
+struct SearchProgress<W> {
+    expanded: usize,
+    queue_size: usize,
+    best_weight: Option<W>,
+}
+
+/// A not-yet-materialized derivation considered while enumerating k-best
+/// trees: "combine the `rank.0`-th best derivation of `from_state` with
+/// the `rank.1`-th best derivation of `near_state` (if any) via
+/// `symbol`". `rank.1` is unused (always 0) for unary transitions.
+struct Candidate<S: SymbolTrait> {
+    to_state: State,
+    from_state: State,
+    near_state: Option<State>,
+    symbol: S,
+    rank: (usize, usize),
+    weight: S::Weight,
+}
+
+impl<S: SymbolTrait> PartialEq for Candidate<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.weight == other.weight
+    }
+}
+
+impl<S: SymbolTrait> Eq for Candidate<S> {}
+
+impl<S: SymbolTrait> PartialOrd for Candidate<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: SymbolTrait> Ord for Candidate<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, as in `TreeNode`, for min-heap behavior under the
+        // semiring's natural order.
+        if self.weight.strictly_better(&other.weight) {
+            Ordering::Greater
+        } else if other.weight.strictly_better(&self.weight) {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
 struct TreeAutomation<S: SymbolTrait> {
     transition: Transition<S>,
-    start_state: Vec<(State, usize)>,
+    start_state: Vec<(State, S::Weight)>,
     final_state: Option<State>,
+    heuristic: Box<dyn Heuristic<S::Weight> + Send + Sync>,
 }
 
 impl<S: SymbolTrait> TreeAutomation<S> {
@@ -106,6 +347,7 @@ impl<S: SymbolTrait> TreeAutomation<S> {
             transition: Transition::new(),
             start_state: Vec::new(),
             final_state: None,
+            heuristic: Box::new(ZeroHeuristic),
         }
     }
 
@@ -117,30 +359,50 @@ impl<S: SymbolTrait> TreeAutomation<S> {
         self.final_state = Some(final_state)
     }
 
-    fn add_initial_state(&mut self, start: State, weight: usize) {
+    fn add_initial_state(&mut self, start: State, weight: S::Weight) {
         self.start_state.push((start, weight));
     }
 
-    fn find_path(&self) -> Option<Rc<TreeNode<S>>> {
+    /// Plug in an admissible heuristic to guide `find_path` (A*) instead of
+    /// exploring purely by accumulated weight. See `Heuristic` for the
+    /// admissibility requirement; omit this call to keep uniform-cost
+    /// behavior.
+    fn set_heuristic(&mut self, heuristic: Box<dyn Heuristic<S::Weight> + Send + Sync>) {
+        self.heuristic = heuristic;
+    }
+
+    fn make_node(
+        &self,
+        state: State,
+        first_child: Option<Arc<TreeNode<S>>>,
+        second_child: Option<Arc<TreeNode<S>>>,
+        symbol: Option<S>,
+        weight: S::Weight,
+    ) -> Arc<TreeNode<S>> {
+        let priority = weight.mul(&self.heuristic.estimate(state));
+        Arc::new(TreeNode {
+            state,
+            first_child,
+            second_child,
+            symbol,
+            weight,
+            priority,
+        })
+    }
+
+    fn find_path(&self) -> Option<Arc<TreeNode<S>>> {
         let mut heap = BinaryHeap::new();
-        let mut used_node: HashMap<State, Rc<TreeNode<S>>> = HashMap::new();
+        let mut used_node: HashMap<State, Arc<TreeNode<S>>> = HashMap::new();
         for (state, cost) in self.start_state.iter() {
-            used_node.insert(
-                state.clone(),
-                Rc::new(TreeNode {
-                    state: state.clone(),
-                    first_child: None,
-                    second_child: None,
-                    symbol: None,
-                    weight: cost.clone(),
-                }),
-            );
-            heap.push(Rc::clone(used_node.get(&state).unwrap()));
+            let node = self.make_node(*state, None, None, None, cost.clone());
+            used_node.insert(*state, Arc::clone(&node));
+            heap.push(node);
         }
 
         while let Some(item) = heap.pop() {
-            // check that is the same
-            if Rc::ptr_eq(&item, used_node.get(&item.state).unwrap()) {
+            // A cheaper node for this state may have superseded `item`
+            // since it was pushed; skip the now-stale entry.
+            if !Arc::ptr_eq(&item, used_node.get(&item.state).unwrap()) {
                 continue;
             }
 
@@ -154,26 +416,30 @@ impl<S: SymbolTrait> TreeAutomation<S> {
                 .iter()
             {
                 let new_ref = if let Some(neighbor) = near {
-                    Rc::new(TreeNode {
-                        state: next.clone(),
-                        first_child: Some(Rc::clone(&item)),
-                        second_child: Some(Rc::clone(used_node.get(&neighbor).unwrap())),
-                        symbol: Some(transition.clone()),
-                        weight: item.weight
-                            + used_node.get(&neighbor).unwrap().weight
-                            + transition.weight(),
-                    })
+                    let neighbor_node = used_node.get(neighbor).unwrap();
+                    self.make_node(
+                        *next,
+                        Some(Arc::clone(&item)),
+                        Some(Arc::clone(neighbor_node)),
+                        Some(transition.clone()),
+                        item.weight
+                            .mul(&neighbor_node.weight)
+                            .mul(&transition.weight()),
+                    )
                 } else {
-                    Rc::new(TreeNode {
-                        state: next.clone(),
-                        first_child: Some(Rc::clone(&item)),
-                        second_child: None,
-                        symbol: Some(transition.clone()),
-                        weight: item.weight + transition.weight(),
-                    })
+                    self.make_node(
+                        *next,
+                        Some(Arc::clone(&item)),
+                        None,
+                        Some(transition.clone()),
+                        item.weight.mul(&transition.weight()),
+                    )
                 };
-                if new_ref.weight < used_node.get(&new_ref.state).map_or(0, |item| item.weight) {
-                    used_node.insert(new_ref.state, Rc::clone(&new_ref));
+                let is_improvement = used_node
+                    .get(&new_ref.state)
+                    .is_none_or(|existing| new_ref.weight.strictly_better(&existing.weight));
+                if is_improvement {
+                    used_node.insert(new_ref.state, Arc::clone(&new_ref));
                     heap.push(new_ref);
                 }
             }
@@ -181,8 +447,1176 @@ impl<S: SymbolTrait> TreeAutomation<S> {
 
         None
     }
+
+    /// Same search as `find_path`, but sharded across `workers` threads
+    /// sharing the frontier and `used_node` best-weights map behind a
+    /// lock, for transition tables large enough that a single thread is
+    /// the bottleneck.
+    ///
+    /// Termination is NOT "stop at the first `final_state` any worker
+    /// pops": a worker can still be mid-expansion, with cheaper nodes not
+    /// yet pushed to the frontier, when another worker pops an accepting
+    /// node. Instead, once some accepting node has been found, every
+    /// worker checks -- after finishing whatever item it's currently
+    /// expanding -- whether the frontier's own minimum-priority node could
+    /// still beat it; only when no worker is mid-expansion (tracked by
+    /// `active`) and the frontier can't improve on `best` (or is empty) is
+    /// the search actually exhausted, which is what `should_stop`
+    /// computes. This preserves the optimality guarantee of `find_path`.
+    ///
+    /// When `progress` is set, a `SearchProgress` snapshot is sent
+    /// roughly every `PROGRESS_INTERVAL` so a caller can show throughput
+    /// and the current best weight.
+    fn find_path_parallel(
+        &self,
+        workers: usize,
+        progress: Option<Sender<SearchProgress<S::Weight>>>,
+    ) -> Option<Arc<TreeNode<S>>>
+    where
+        S: Send + Sync,
+        S::Weight: Send + Sync,
+        Self: Sync,
+    {
+        let workers = workers.max(1);
+        let frontier: Mutex<BinaryHeap<Arc<TreeNode<S>>>> = Mutex::new(BinaryHeap::new());
+        let used_node: Mutex<HashMap<State, Arc<TreeNode<S>>>> = Mutex::new(HashMap::new());
+        let best: Mutex<Option<Arc<TreeNode<S>>>> = Mutex::new(None);
+        let expanded = AtomicUsize::new(0);
+        let active = AtomicUsize::new(0);
+        let done = AtomicBool::new(false);
+
+        {
+            let mut frontier = frontier.lock().unwrap();
+            let mut used_node = used_node.lock().unwrap();
+            for (state, cost) in self.start_state.iter() {
+                let node = self.make_node(*state, None, None, None, cost.clone());
+                used_node.insert(*state, Arc::clone(&node));
+                frontier.push(node);
+            }
+        }
+
+        std::thread::scope(|scope| {
+            let frontier = &frontier;
+            let used_node = &used_node;
+            let best = &best;
+            let expanded = &expanded;
+            let active = &active;
+            let done = &done;
+            for _ in 0..workers {
+                let progress = progress.clone();
+                scope.spawn(move || {
+                    let mut last_report = Instant::now();
+                    loop {
+                        if done.load(AtomicOrdering::Acquire) {
+                            return;
+                        }
+
+                        // Increment `active` in the same critical section as the pop,
+                        // not after: otherwise another worker can observe an empty
+                        // frontier and active == 0 in the window between this worker
+                        // removing the node and marking itself active, and wrongly
+                        // declare the search done while this node is still about to be
+                        // expanded (and possibly push the optimal path's next node).
+                        let item = {
+                            let mut frontier = frontier.lock().unwrap();
+                            let item = frontier.pop();
+                            if item.is_some() {
+                                active.fetch_add(1, AtomicOrdering::AcqRel);
+                            }
+                            item
+                        };
+                        let Some(item) = item else {
+                            if Self::should_stop(frontier, best, active) {
+                                done.store(true, AtomicOrdering::Release);
+                                return;
+                            }
+                            std::thread::yield_now();
+                            continue;
+                        };
+
+                        let is_current = {
+                            let used_node = used_node.lock().unwrap();
+                            Arc::ptr_eq(&item, used_node.get(&item.state).unwrap())
+                        };
+                        if !is_current {
+                            active.fetch_sub(1, AtomicOrdering::AcqRel);
+                            continue;
+                        }
+
+                        expanded.fetch_add(1, AtomicOrdering::Relaxed);
+
+                        if item.state == self.final_state.unwrap() {
+                            let mut best_guard = best.lock().unwrap();
+                            if best_guard
+                                .as_ref()
+                                .is_none_or(|existing| item.weight.strictly_better(&existing.weight))
+                            {
+                                *best_guard = Some(item);
+                            }
+                            drop(best_guard);
+                            active.fetch_sub(1, AtomicOrdering::AcqRel);
+                            if Self::should_stop(frontier, best, active) {
+                                done.store(true, AtomicOrdering::Release);
+                                return;
+                            }
+                            continue;
+                        }
+
+                        let candidates = {
+                            let used_node = used_node.lock().unwrap();
+                            self.transition.transition_list(item.state, &used_node)
+                        };
+
+                        for (near, transition, next) in candidates.iter() {
+                            let new_ref = {
+                                let used_node = used_node.lock().unwrap();
+                                if let Some(neighbor) = near {
+                                    let neighbor_node = used_node.get(neighbor).unwrap();
+                                    self.make_node(
+                                        *next,
+                                        Some(Arc::clone(&item)),
+                                        Some(Arc::clone(neighbor_node)),
+                                        Some(transition.clone()),
+                                        item.weight
+                                            .mul(&neighbor_node.weight)
+                                            .mul(&transition.weight()),
+                                    )
+                                } else {
+                                    self.make_node(
+                                        *next,
+                                        Some(Arc::clone(&item)),
+                                        None,
+                                        Some(transition.clone()),
+                                        item.weight.mul(&transition.weight()),
+                                    )
+                                }
+                            };
+
+                            let mut used_node = used_node.lock().unwrap();
+                            let is_improvement = used_node.get(&new_ref.state).is_none_or(|existing| {
+                                new_ref.weight.strictly_better(&existing.weight)
+                            });
+                            if is_improvement {
+                                used_node.insert(new_ref.state, Arc::clone(&new_ref));
+                                drop(used_node);
+                                frontier.lock().unwrap().push(new_ref);
+                            }
+                        }
+
+                        active.fetch_sub(1, AtomicOrdering::AcqRel);
+
+                        if let Some(sender) = &progress {
+                            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                                // Snapshot each lock's value in its own statement, in the
+                                // same best-then-frontier order as `should_stop`, so the
+                                // guard from one is dropped before the other is acquired.
+                                // Holding both at once here (as a struct-literal field
+                                // evaluated in `frontier`-then-`best` order, with both
+                                // temporaries alive until the `send` call) deadlocks
+                                // against `should_stop`'s opposite locking order.
+                                let best_weight = best.lock().unwrap().as_ref().map(|n| n.weight.clone());
+                                let queue_size = frontier.lock().unwrap().len();
+                                let _ = sender.send(SearchProgress {
+                                    expanded: expanded.load(AtomicOrdering::Relaxed),
+                                    queue_size,
+                                    best_weight,
+                                });
+                                last_report = Instant::now();
+                            }
+                        }
+
+                        if Self::should_stop(frontier, best, active) {
+                            done.store(true, AtomicOrdering::Release);
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        let winner = best.lock().unwrap().take();
+        winner
+    }
+
+    /// True iff the search is actually exhausted: no worker is mid-expansion
+    /// (`active == 0`, so nothing still might push a cheaper node) and
+    /// either the frontier is empty or its minimum-priority node can't beat
+    /// `best` (or there's no `best` yet and the frontier is empty, meaning
+    /// `final_state` is unreachable). Checking only the first popped
+    /// `final_state`, without this, is the race that breaks optimality.
+    fn should_stop(
+        frontier: &Mutex<BinaryHeap<Arc<TreeNode<S>>>>,
+        best: &Mutex<Option<Arc<TreeNode<S>>>>,
+        active: &AtomicUsize,
+    ) -> bool {
+        let best = best.lock().unwrap();
+        let frontier = frontier.lock().unwrap();
+        // Checking `active` before taking `frontier`'s lock is a TOCTOU: a
+        // worker can pop the last frontier node and mark itself active in
+        // the gap between that load and this function acquiring the lock,
+        // which this function would never observe. Pop-and-increment
+        // happens atomically under `frontier`'s lock, so checking `active`
+        // only after acquiring that same lock closes the gap -- any
+        // in-flight pop-and-increment has either fully completed (and is
+        // visible here) or is still waiting for this guard to drop.
+        if active.load(AtomicOrdering::Acquire) != 0 {
+            return false;
+        }
+        match best.as_ref() {
+            Some(best) => frontier
+                .peek()
+                .is_none_or(|top| !top.priority.strictly_better(&best.weight)),
+            None => frontier.is_empty(),
+        }
+    }
+
+    /// Builds a `Candidate` combining the `rank.0`-th best derivation of
+    /// `from_state` with the `rank.1`-th best derivation of `near_state`
+    /// (if present), returning `None` when that rank hasn't been
+    /// discovered yet (`derivations` is still growing lazily).
+    fn seed_candidate(
+        &self,
+        derivations: &HashMap<State, Vec<Arc<TreeNode<S>>>>,
+        from_state: State,
+        near_state: Option<State>,
+        symbol: S,
+        to_state: State,
+        rank: (usize, usize),
+    ) -> Option<Candidate<S>> {
+        let from_node = derivations.get(&from_state)?.get(rank.0)?;
+        let weight = if let Some(near) = near_state {
+            let near_node = derivations.get(&near)?.get(rank.1)?;
+            from_node.weight.mul(&near_node.weight).mul(&symbol.weight())
+        } else {
+            from_node.weight.mul(&symbol.weight())
+        };
+        Some(Candidate {
+            to_state,
+            from_state,
+            near_state,
+            symbol,
+            rank,
+            weight,
+        })
+    }
+
+    fn materialize_candidate(
+        &self,
+        derivations: &HashMap<State, Vec<Arc<TreeNode<S>>>>,
+        candidate: &Candidate<S>,
+    ) -> Arc<TreeNode<S>> {
+        let from_node = Arc::clone(&derivations[&candidate.from_state][candidate.rank.0]);
+        let second_child = candidate
+            .near_state
+            .map(|near| Arc::clone(&derivations[&near][candidate.rank.1]));
+        self.make_node(
+            candidate.to_state,
+            Some(from_node),
+            second_child,
+            Some(candidate.symbol.clone()),
+            candidate.weight.clone(),
+        )
+    }
+
+    /// Returns the `k` best accepting trees, best first under the
+    /// semiring's natural order (cheapest first, in nondecreasing weight,
+    /// for `Tropical`), for ranked/ambiguous parses rather than a single
+    /// optimum.
+    ///
+    /// Implements the lazy k-best-derivations algorithm: a best-first pass
+    /// first records the 1-best subtree for every reachable state (same as
+    /// `find_path`), then `KBestIndex::ensure_rank` lazily grows
+    /// `final_state`'s derivation list `D` up to `k` entries, recursing to
+    /// grow any other state's `D` it depends on along the way.
+    fn find_k_best(&self, k: usize) -> Vec<Arc<TreeNode<S>>> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let Some(final_state) = self.final_state else {
+            return Vec::new();
+        };
+
+        let mut heap = BinaryHeap::new();
+        let mut used_node: HashMap<State, Arc<TreeNode<S>>> = HashMap::new();
+        for (state, cost) in self.start_state.iter() {
+            let node = self.make_node(*state, None, None, None, cost.clone());
+            used_node.insert(*state, Arc::clone(&node));
+            heap.push(node);
+        }
+        while let Some(item) = heap.pop() {
+            if !Arc::ptr_eq(&item, used_node.get(&item.state).unwrap()) {
+                continue;
+            }
+            for (near, transition, next) in self
+                .transition
+                .transition_list(item.state, &used_node)
+                .iter()
+            {
+                let new_ref = if let Some(neighbor) = near {
+                    let neighbor_node = used_node.get(neighbor).unwrap();
+                    self.make_node(
+                        *next,
+                        Some(Arc::clone(&item)),
+                        Some(Arc::clone(neighbor_node)),
+                        Some(transition.clone()),
+                        item.weight
+                            .mul(&neighbor_node.weight)
+                            .mul(&transition.weight()),
+                    )
+                } else {
+                    self.make_node(
+                        *next,
+                        Some(Arc::clone(&item)),
+                        None,
+                        Some(transition.clone()),
+                        item.weight.mul(&transition.weight()),
+                    )
+                };
+                let is_improvement = used_node
+                    .get(&new_ref.state)
+                    .is_none_or(|existing| new_ref.weight.strictly_better(&existing.weight));
+                if is_improvement {
+                    used_node.insert(new_ref.state, Arc::clone(&new_ref));
+                    heap.push(new_ref);
+                }
+            }
+        }
+
+        if !used_node.contains_key(&final_state) {
+            return Vec::new();
+        }
+
+        // D[v]: derivations of state v discovered so far, nondecreasing by
+        // weight. Every reachable state already has its 1-best derivation.
+        let derivations: HashMap<State, Vec<Arc<TreeNode<S>>>> = used_node
+            .iter()
+            .map(|(state, node)| (*state, vec![Arc::clone(node)]))
+            .collect();
+
+        let incoming = self.transition.incoming();
+        let mut index = KBestIndex::new(self, &incoming, derivations);
+        index.ensure_rank(final_state, k - 1);
+
+        index.derivations.remove(&final_state).map_or(Vec::new(), |mut v| {
+            v.truncate(k);
+            v
+        })
+    }
+
+    /// Evaluates `input` bottom-up and returns the best weight (under the
+    /// semiring's `add`; the minimum for `Tropical`) with which it is
+    /// accepted (i.e. reaches `final_state`), or `None` if it isn't
+    /// accepted at all. Unlike `find_path`/`find_k_best`, which
+    /// *generate* a cheapest tree, this *recognizes* a tree the caller
+    /// already has.
+    fn recognize(&self, input: &InputTree<S>) -> Option<S::Weight> {
+        let final_state = self.final_state?;
+        self.recognize_weights(input).get(&final_state).cloned()
+    }
+
+    /// For every state reachable at the root of `input`, the best (`add`-
+    /// combined) weight of a derivation of `input` landing in that state.
+    /// Reads `Transition::items` directly in the forward (from -> to)
+    /// direction, without `transition_list`'s `used_node` filter and
+    /// unlike `incoming`, which is keyed by the produced state.
+    fn recognize_weights(&self, input: &InputTree<S>) -> HashMap<State, S::Weight> {
+        let mut weights = HashMap::new();
+        let relax = |weights: &mut HashMap<State, S::Weight>, state: State, candidate: S::Weight| {
+            weights
+                .entry(state)
+                .and_modify(|existing| *existing = existing.add(&candidate))
+                .or_insert(candidate);
+        };
+
+        match input {
+            InputTree::Leaf => {
+                for (state, cost) in self.start_state.iter() {
+                    relax(&mut weights, *state, cost.clone());
+                }
+            }
+            InputTree::Unary(symbol, child) => {
+                let child_weights = self.recognize_weights(child);
+                for (&from, entries) in self.transition.items.iter() {
+                    let Some(from_weight) = child_weights.get(&from) else {
+                        continue;
+                    };
+                    for (near, transition_symbol, end) in entries.iter() {
+                        if near.is_some() || transition_symbol != symbol {
+                            continue;
+                        }
+                        relax(&mut weights, *end, from_weight.mul(&symbol.weight()));
+                    }
+                }
+            }
+            InputTree::Binary(symbol, left, right) => {
+                let left_weights = self.recognize_weights(left);
+                let right_weights = self.recognize_weights(right);
+                for (&from, entries) in self.transition.items.iter() {
+                    let Some(left_weight) = left_weights.get(&from) else {
+                        continue;
+                    };
+                    for (near, transition_symbol, end) in entries.iter() {
+                        let Some(near_state) = near else { continue };
+                        if transition_symbol != symbol {
+                            continue;
+                        }
+                        let Some(right_weight) = right_weights.get(near_state) else {
+                            continue;
+                        };
+                        relax(
+                            &mut weights,
+                            *end,
+                            left_weight.mul(right_weight).mul(&symbol.weight()),
+                        );
+                    }
+                }
+            }
+        }
+
+        weights
+    }
+}
+
+/// Lazily grows `find_k_best`'s per-state derivation lists `D`, recursing
+/// into whichever other states a candidate's weight depends on before
+/// building it, instead of giving up on that candidate when a dependency
+/// isn't ready yet. `cand[v]` is the per-state candidate heap from the
+/// lazy k-best-derivations algorithm: seeded from `v`'s incoming
+/// transitions on first use, then grown rank by rank as `D[v]` extends.
+struct KBestIndex<'a, S: SymbolTrait> {
+    automaton: &'a TreeAutomation<S>,
+    incoming: &'a IncomingEdges<S>,
+    derivations: HashMap<State, Vec<Arc<TreeNode<S>>>>,
+    cand: HashMap<State, BinaryHeap<Candidate<S>>>,
+}
+
+impl<'a, S: SymbolTrait> KBestIndex<'a, S> {
+    /// `derivations` must already hold every reachable state's 1-best
+    /// derivation, as found by `find_k_best`'s initial best-first pass.
+    fn new(
+        automaton: &'a TreeAutomation<S>,
+        incoming: &'a IncomingEdges<S>,
+        derivations: HashMap<State, Vec<Arc<TreeNode<S>>>>,
+    ) -> Self {
+        KBestIndex {
+            automaton,
+            incoming,
+            derivations,
+            cand: HashMap::new(),
+        }
+    }
+
+    /// Ensures `derivations[state]` holds at least `rank + 1` entries,
+    /// returning whether it does (false means `state` has fewer than
+    /// `rank + 1` derivations in total). Grows it by repeatedly popping
+    /// `state`'s candidate heap -- seeding the heap from `incoming` on
+    /// first use -- and awakening each popped candidate's successors.
+    /// Popping a candidate may recurse into `ensure_rank` for another
+    /// state it depends on, which is what makes a dependency that isn't
+    /// ready yet get computed on demand rather than dropped.
+    fn ensure_rank(&mut self, state: State, rank: usize) -> bool {
+        if self.derivations.get(&state).map_or(0, Vec::len) > rank {
+            return true;
+        }
+        if !self.cand.contains_key(&state) {
+            let seeded = self.seed_initial(state);
+            self.cand.insert(state, seeded);
+        }
+        while self.derivations.get(&state).map_or(0, Vec::len) <= rank {
+            let Some(candidate) = self.cand.get_mut(&state).unwrap().pop() else {
+                return false;
+            };
+            let node = self.automaton.materialize_candidate(&self.derivations, &candidate);
+            self.derivations.entry(state).or_default().push(node);
+            self.awaken_successors(&candidate);
+        }
+        true
+    }
+
+    /// Seeds `state`'s candidate heap from its incoming transitions: rank
+    /// `(0, 0)` for every transition except the one that actually produced
+    /// the already-known `D[state][0]` (re-seeding that at `(0, 0)` would
+    /// just duplicate it), which seeds its successor ranks instead.
+    fn seed_initial(&mut self, state: State) -> BinaryHeap<Candidate<S>> {
+        let mut heap = BinaryHeap::new();
+        let Some(transitions) = self.incoming.get(&state) else {
+            return heap;
+        };
+        let transitions = transitions.clone();
+        let best = Arc::clone(&self.derivations[&state][0]);
+        for (from, near, symbol) in transitions {
+            let is_best_edge = best.first_child.as_ref().is_some_and(|c| c.state == from)
+                && best.second_child.as_ref().map(|c| c.state) == near
+                && best.symbol.as_ref() == Some(&symbol);
+            let seed_ranks: &[(usize, usize)] = if is_best_edge {
+                if near.is_some() {
+                    &[(1, 0), (0, 1)]
+                } else {
+                    &[(1, 0)]
+                }
+            } else {
+                &[(0, 0)]
+            };
+            for &rank in seed_ranks {
+                if let Some(candidate) = self.try_build(from, near, symbol.clone(), state, rank) {
+                    heap.push(candidate);
+                }
+            }
+        }
+        heap
+    }
+
+    /// Builds the candidate for `(from, near, symbol) -> to` at `rank`,
+    /// first recursively ensuring `from` (and `near`, if present) actually
+    /// have a derivation at that rank -- growing them lazily if they
+    /// don't yet -- rather than giving up when they aren't ready.
+    fn try_build(
+        &mut self,
+        from: State,
+        near: Option<State>,
+        symbol: S,
+        to: State,
+        rank: (usize, usize),
+    ) -> Option<Candidate<S>> {
+        if !self.ensure_rank(from, rank.0) {
+            return None;
+        }
+        if let Some(near_state) = near {
+            if !self.ensure_rank(near_state, rank.1) {
+                return None;
+            }
+        }
+        self.automaton
+            .seed_candidate(&self.derivations, from, near, symbol, to, rank)
+    }
+
+    /// After popping `candidate`, pushes its successors -- the same edge
+    /// at rank `(i + 1, j)` and `(i, j + 1)` (just `(i + 1, j)` for unary
+    /// transitions) -- onto `candidate.to_state`'s candidate heap.
+    fn awaken_successors(&mut self, candidate: &Candidate<S>) {
+        let (i, j) = candidate.rank;
+        let next_ranks: &[(usize, usize)] = if candidate.near_state.is_some() {
+            &[(i + 1, j), (i, j + 1)]
+        } else {
+            &[(i + 1, j)]
+        };
+        for &rank in next_ranks {
+            if let Some(next) = self.try_build(
+                candidate.from_state,
+                candidate.near_state,
+                candidate.symbol.clone(),
+                candidate.to_state,
+                rank,
+            ) {
+                self.cand
+                    .entry(candidate.to_state)
+                    .or_default()
+                    .push(next);
+            }
+        }
+    }
+}
+
+/// Decodes every `(near, symbol, to)` entry packed into `data` back to
+/// back, in the layout `save` writes: `[has_near: u8][near if present]
+/// [symbol_len][symbol_bytes][to]`. Shared by `TreeAutomation::load`
+/// (applied to one state's slice of a fully-read file) and
+/// `MappedAutomation::transition_list` (applied to one state's slice of
+/// the memory-mapped file), so both stay in sync with `save`'s format.
+fn decode_transition_entries<S: SymbolTrait + Serializable>(
+    mut data: &[u8],
+) -> io::Result<Vec<(Option<State>, S, State)>> {
+    let mut result = Vec::new();
+    while !data.is_empty() {
+        let has_near = data[0];
+        data = data.get(1..).ok_or_else(|| truncated("transition entry"))?;
+        let near = if has_near == 1 {
+            let (state, n) = usize::decode(data)?;
+            data = data.get(n..).ok_or_else(|| truncated("transition entry"))?;
+            Some(state)
+        } else {
+            None
+        };
+        let (symbol_len, n) = usize::decode(data)?;
+        data = data.get(n..).ok_or_else(|| truncated("transition entry"))?;
+        let symbol_bytes = data
+            .get(..symbol_len)
+            .ok_or_else(|| truncated("transition entry"))?;
+        let (symbol, _) = S::decode(symbol_bytes)?;
+        data = data
+            .get(symbol_len..)
+            .ok_or_else(|| truncated("transition entry"))?;
+        let (to, n) = usize::decode(data)?;
+        data = data.get(n..).ok_or_else(|| truncated("transition entry"))?;
+        result.push((near, symbol, to));
+    }
+    Ok(result)
+}
+
+impl<S: SymbolTrait + Serializable> TreeAutomation<S>
+where
+    S::Weight: Serializable,
+{
+    /// One more than the largest state referenced anywhere in this
+    /// automaton, used to size `save`'s offset table.
+    fn state_count(&self) -> usize {
+        let mut max_state = 0;
+        for (state, _) in self.start_state.iter() {
+            max_state = max_state.max(*state);
+        }
+        if let Some(final_state) = self.final_state {
+            max_state = max_state.max(final_state);
+        }
+        for (&from, entries) in self.transition.items.iter() {
+            max_state = max_state.max(from);
+            for (near, _, to) in entries.iter() {
+                if let Some(near) = near {
+                    max_state = max_state.max(*near);
+                }
+                max_state = max_state.max(*to);
+            }
+        }
+        max_state + 1
+    }
+
+    /// Writes this automaton to a compact binary format at `path`: a
+    /// header (state count, `start_state`, `final_state`), then a flat
+    /// transition table grouped by `from` state (matching
+    /// `Transition::items`) behind a CSR-style offset table, so a loader
+    /// can locate any state's entries without scanning the whole file.
+    /// Each entry encodes `(Option<State>, symbol_bytes, to_state)`.
+    fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let state_count = self.state_count();
+
+        let mut header = Vec::new();
+        state_count.encode(&mut header);
+        self.start_state.len().encode(&mut header);
+        for (state, weight) in self.start_state.iter() {
+            state.encode(&mut header);
+            weight.encode(&mut header);
+        }
+        match self.final_state {
+            Some(state) => {
+                header.push(1);
+                state.encode(&mut header);
+            }
+            None => header.push(0),
+        }
+
+        // offsets[i]..offsets[i + 1] is state i's byte range within
+        // `transition_data`; the trailing sentinel lets the last state's
+        // range be computed the same way as every other state's.
+        let mut transition_data = Vec::new();
+        let mut offsets = Vec::with_capacity(state_count + 1);
+        for state in 0..state_count {
+            offsets.push(transition_data.len());
+            if let Some(entries) = self.transition.items.get(&state) {
+                for (near, symbol, to) in entries.iter() {
+                    match near {
+                        Some(near) => {
+                            transition_data.push(1);
+                            near.encode(&mut transition_data);
+                        }
+                        None => transition_data.push(0),
+                    }
+                    let mut symbol_bytes = Vec::new();
+                    symbol.encode(&mut symbol_bytes);
+                    symbol_bytes.len().encode(&mut transition_data);
+                    transition_data.extend_from_slice(&symbol_bytes);
+                    to.encode(&mut transition_data);
+                }
+            }
+        }
+        offsets.push(transition_data.len());
+
+        let mut out = header;
+        for offset in offsets.iter() {
+            offset.encode(&mut out);
+        }
+        out.extend_from_slice(&transition_data);
+
+        std::fs::write(path, out)
+    }
+
+    /// Reads back a file written by `save`, fully materializing
+    /// `Transition`'s `HashMap<State, Vec<...>>` as usual. For files too
+    /// large to comfortably hold in memory, use `MappedAutomation::load`
+    /// instead. The loaded automaton gets a fresh `ZeroHeuristic`, since
+    /// a `Heuristic` is a runtime plug-in rather than persisted state.
+    fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let (state_count, start_state, final_state, offsets, transition_data_start) =
+            decode_header::<S>(&bytes)?;
+
+        let mut transition = Transition::new();
+        for state in 0..state_count {
+            let start = transition_data_start + offsets[state];
+            let end = transition_data_start + offsets[state + 1];
+            if start == end {
+                continue;
+            }
+            let range = bytes.get(start..end).ok_or_else(|| truncated("transition data"))?;
+            let entries = decode_transition_entries::<S>(range)?;
+            transition.items.insert(state, entries);
+        }
+
+        Ok(TreeAutomation {
+            transition,
+            start_state,
+            final_state,
+            heuristic: Box::new(ZeroHeuristic),
+        })
+    }
+}
+
+/// `decode_header`'s result: state count, the decoded `(state, weight)`
+/// `start_state` table, `final_state`, the CSR offset table, and the byte
+/// offset where `transition_data` begins.
+type DecodedHeader<W> = (usize, Vec<(State, W)>, Option<State>, Vec<usize>, usize);
+
+/// Parses `save`'s header (everything up to, but not
+/// including, `transition_data`), returning the state count, the decoded
+/// `start_state` and `final_state`, the CSR offset table, and the byte
+/// offset where `transition_data` begins. Shared by `TreeAutomation::load`
+/// and `MappedAutomation::load`, which differ only in whether they eagerly
+/// decode the transition entries that follow.
+fn decode_header<S: SymbolTrait + Serializable>(bytes: &[u8]) -> io::Result<DecodedHeader<S::Weight>>
+where
+    S::Weight: Serializable,
+{
+    fn at(bytes: &[u8], cursor: usize) -> io::Result<&[u8]> {
+        bytes.get(cursor..).ok_or_else(|| truncated("header"))
+    }
+
+    let mut cursor = 0;
+
+    let (state_count, n) = usize::decode(at(bytes, cursor)?)?;
+    cursor += n;
+
+    let (start_count, n) = usize::decode(at(bytes, cursor)?)?;
+    cursor += n;
+    let mut start_state = Vec::with_capacity(start_count);
+    for _ in 0..start_count {
+        let (state, n) = usize::decode(at(bytes, cursor)?)?;
+        cursor += n;
+        let (weight, n) = S::Weight::decode(at(bytes, cursor)?)?;
+        cursor += n;
+        start_state.push((state, weight));
+    }
+
+    let has_final = *bytes.get(cursor).ok_or_else(|| truncated("header"))?;
+    cursor += 1;
+    let final_state = if has_final == 1 {
+        let (state, n) = usize::decode(at(bytes, cursor)?)?;
+        cursor += n;
+        Some(state)
+    } else {
+        None
+    };
+
+    let mut offsets = Vec::with_capacity(state_count + 1);
+    for _ in 0..=state_count {
+        let (offset, n) = usize::decode(at(bytes, cursor)?)?;
+        cursor += n;
+        offsets.push(offset);
+    }
+
+    Ok((state_count, start_state, final_state, offsets, cursor))
+}
+
+/// A `TreeAutomation` loaded from a memory-mapped on-disk file written by
+/// `TreeAutomation::save`. `start_state` and `final_state` are parsed
+/// eagerly (cheap, header-only), but transition entries are decoded
+/// lazily, read directly out of the mapped buffer via `transition_list`,
+/// so very large automata can be queried with minimal resident memory
+/// instead of materializing the full `HashMap<State, Vec<...>>` up front.
+struct MappedAutomation<S: SymbolTrait> {
+    mmap: Mmap,
+    start_state: Vec<(State, S::Weight)>,
+    final_state: Option<State>,
+    offsets: Vec<usize>,
+    transition_data_start: usize,
+    symbol: PhantomData<S>,
+}
+
+impl<S: SymbolTrait + Serializable> MappedAutomation<S>
+where
+    S::Weight: Serializable,
+{
+    fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // Safety: the mapped file is treated as read-only data produced
+        // by `save`; we never write through `mmap`, and the caller is
+        // responsible for the file not being concurrently truncated.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let (_, start_state, final_state, offsets, transition_data_start) =
+            decode_header::<S>(&mmap)?;
+
+        Ok(MappedAutomation {
+            mmap,
+            start_state,
+            final_state,
+            offsets,
+            transition_data_start,
+            symbol: PhantomData,
+        })
+    }
+
+    /// `Transition::transition_list`'s counterpart for a mapped
+    /// automaton: scans `from`'s byte range directly out of `mmap`,
+    /// decoding entries on demand instead of a preconstructed
+    /// `HashMap<State, Vec<...>>`. Fails rather than panics if `mmap`
+    /// doesn't actually hold the bytes `offsets` claims it does (a
+    /// truncated or corrupt file).
+    fn transition_list(&self, from: State) -> io::Result<Vec<(Option<State>, S, State)>> {
+        let (Some(&start), Some(&end)) = (self.offsets.get(from), self.offsets.get(from + 1))
+        else {
+            return Ok(Vec::new());
+        };
+        let start = self.transition_data_start + start;
+        let end = self.transition_data_start + end;
+        let range = self.mmap.get(start..end).ok_or_else(|| truncated("mapped transition range"))?;
+        decode_transition_entries(range)
+    }
 }
 
 fn main() {
     println!("HELLO");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    struct TestSymbol {
+        id: u8,
+        weight: Tropical,
+    }
+
+    fn sym(id: u8, weight: usize) -> TestSymbol {
+        TestSymbol {
+            id,
+            weight: Tropical(weight),
+        }
+    }
+
+    impl SymbolTrait for TestSymbol {
+        type Weight = Tropical;
+        fn weight(&self) -> Tropical {
+            self.weight
+        }
+    }
+
+    impl Serializable for TestSymbol {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            buf.push(self.id);
+            self.weight.encode(buf);
+        }
+
+        fn decode(buf: &[u8]) -> io::Result<(Self, usize)> {
+            let id = *buf.first().ok_or_else(|| truncated("TestSymbol"))?;
+            let (weight, n) = Tropical::decode(buf.get(1..).ok_or_else(|| truncated("TestSymbol"))?)?;
+            Ok((TestSymbol { id, weight }, 1 + n))
+        }
+    }
+
+    /// An estimate supplied for each state by a test, for plugging into
+    /// `set_heuristic`.
+    struct TableHeuristic(HashMap<State, Tropical>);
+
+    impl Heuristic<Tropical> for TableHeuristic {
+        fn estimate(&self, state: State) -> Tropical {
+            self.0.get(&state).copied().unwrap_or_else(Tropical::one)
+        }
+    }
+
+    // chunk0-1: A* guidance via a plugged-in admissible heuristic.
+    #[test]
+    fn find_path_with_heuristic_finds_the_same_optimum_as_uniform_cost() {
+        // 0 --a(5)--> 1 --c(1)--> 3 (final), total 6
+        // 0 --b(1)--> 2 --d(1)--> 3 (final), total 2 (cheapest)
+        let mut automaton: TreeAutomation<TestSymbol> = TreeAutomation::new();
+        automaton.add_initial_state(0, Tropical(0));
+        automaton.add_transition(sym(b'a', 5), StatePair::Single(0), 1);
+        automaton.add_transition(sym(b'b', 1), StatePair::Single(0), 2);
+        automaton.add_transition(sym(b'c', 1), StatePair::Single(1), 3);
+        automaton.add_transition(sym(b'd', 1), StatePair::Single(2), 3);
+        automaton.set_final_state(3);
+
+        let uniform = automaton.find_path().expect("reachable");
+        assert_eq!(uniform.weight.0, 2);
+
+        // A perfect (hence admissible) remaining-cost estimate per state.
+        let mut table = HashMap::new();
+        table.insert(0, Tropical(2));
+        table.insert(1, Tropical(1));
+        table.insert(2, Tropical(1));
+        table.insert(3, Tropical(0));
+        automaton.set_heuristic(Box::new(TableHeuristic(table)));
+
+        let guided = automaton.find_path().expect("reachable");
+        assert_eq!(guided.weight.0, 2);
+    }
+
+    // chunk0-2: k-best enumeration must grow a dependency's rank list
+    // lazily instead of dropping a candidate whose rank isn't ready yet.
+    #[test]
+    fn find_k_best_awakens_a_successor_once_its_dependency_grows() {
+        // Two start states both feed state 1 via the same unary symbol
+        // `p`, then state 1 feeds the sole final state via `q`. Final's
+        // only incoming edge needs state 1's rank 1, which doesn't exist
+        // until state 1's own derivations are grown on demand.
+        let mut automaton: TreeAutomation<TestSymbol> = TreeAutomation::new();
+        automaton.add_initial_state(0, Tropical(1));
+        automaton.add_initial_state(10, Tropical(2));
+        automaton.add_transition(sym(b'p', 0), StatePair::Single(0), 1);
+        automaton.add_transition(sym(b'p', 0), StatePair::Single(10), 1);
+        automaton.add_transition(sym(b'q', 0), StatePair::Single(1), 2);
+        automaton.set_final_state(2);
+
+        let best3 = automaton.find_k_best(3);
+        let weights: Vec<usize> = best3.iter().map(|node| node.weight.0).collect();
+        assert_eq!(weights, vec![1, 2]);
+    }
+
+    // chunk0-3: bottom-up recognition of a concrete input tree.
+    #[test]
+    fn recognize_accepts_a_matching_tree_and_rejects_a_mismatched_one() {
+        let mut automaton: TreeAutomation<TestSymbol> = TreeAutomation::new();
+        automaton.add_initial_state(0, Tropical(0));
+        automaton.add_transition(
+            sym(b'+', 2),
+            StatePair::Pair(0, 0),
+            1,
+        );
+        automaton.add_transition(sym(b'!', 1), StatePair::Single(1), 2);
+        automaton.set_final_state(2);
+
+        let accepted = InputTree::Unary(
+            sym(b'!', 1),
+            Box::new(InputTree::Binary(
+                sym(b'+', 2),
+                Box::new(InputTree::Leaf),
+                Box::new(InputTree::Leaf),
+            )),
+        );
+        assert_eq!(automaton.recognize(&accepted), Some(Tropical(3)));
+
+        let wrong_symbol = InputTree::Unary(
+            sym(b'?', 1),
+            Box::new(InputTree::Binary(
+                sym(b'+', 2),
+                Box::new(InputTree::Leaf),
+                Box::new(InputTree::Leaf),
+            )),
+        );
+        assert_eq!(automaton.recognize(&wrong_symbol), None);
+    }
+
+    // chunk0-4: the parallel search must agree with the sequential one.
+    //
+    // Run many times over a graph wide and deep enough for all workers to
+    // race over the frontier, with a real progress channel drained on
+    // another thread: this exercises the active-count races between
+    // workers, which a single pass over a tiny graph never touches. A
+    // progress report is only sent by a worker whose run lasts past a
+    // `PROGRESS_INTERVAL` tick, which a search this size may not reach.
+    #[test]
+    fn find_path_parallel_matches_find_path() {
+        let mut automaton: TreeAutomation<TestSymbol> = TreeAutomation::new();
+        automaton.add_initial_state(0, Tropical(0));
+        // Many wide diamonds in series, so there are lots of equally
+        // plausible-looking partial paths for workers to race over.
+        let mut state = 0;
+        let mut next_id = 1;
+        for step in 0..30 {
+            let mut next_layer = Vec::new();
+            for branch in 0..4 {
+                let mid = next_id;
+                next_id += 1;
+                automaton.add_transition(
+                    sym(b'a' + branch as u8, 1 + (step + branch) % 5),
+                    StatePair::Single(state),
+                    mid,
+                );
+                next_layer.push(mid);
+            }
+            let next = next_id;
+            next_id += 1;
+            for (branch, mid) in next_layer.into_iter().enumerate() {
+                automaton.add_transition(sym(b'A' + branch as u8, 1), StatePair::Single(mid), next);
+            }
+            state = next;
+        }
+        automaton.set_final_state(state);
+
+        let sequential = automaton.find_path().expect("reachable");
+
+        for _ in 0..20 {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let parallel = std::thread::scope(|scope| {
+                let drain = scope.spawn(move || {
+                    let mut reports = Vec::new();
+                    while let Ok(report) = receiver.recv() {
+                        reports.push(report);
+                    }
+                    reports
+                });
+                let parallel = automaton
+                    .find_path_parallel(8, Some(sender))
+                    .expect("reachable");
+                drain.join().unwrap();
+                parallel
+            });
+            assert_eq!(parallel.weight.0, sequential.weight.0);
+        }
+    }
+
+    // chunk0-5: the search engine is generic over the weight semiring.
+    //
+    // Viterbi weights are probabilities in `[0, 1]`, represented as a
+    // fixed-point `u32` numerator over `ONE` so `mul` stays exact integer
+    // arithmetic. `ONE` (certainty) is the top element and `mul` of two
+    // probabilities can never exceed either factor, so this is a genuine
+    // *superior* semiring per the `Semiring` doc comment above, unlike
+    // plain unbounded integer products.
+    const VITERBI_ONE: u32 = 1_000_000;
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    struct Viterbi(u32);
+
+    impl Semiring for Viterbi {
+        fn zero() -> Self {
+            Viterbi(0)
+        }
+        fn one() -> Self {
+            Viterbi(VITERBI_ONE)
+        }
+        fn add(&self, other: &Self) -> Self {
+            Viterbi(self.0.max(other.0))
+        }
+        fn mul(&self, other: &Self) -> Self {
+            Viterbi(((self.0 as u64 * other.0 as u64) / VITERBI_ONE as u64) as u32)
+        }
+    }
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    struct ProbSymbol {
+        id: u8,
+        weight: Viterbi,
+    }
+
+    impl SymbolTrait for ProbSymbol {
+        type Weight = Viterbi;
+        fn weight(&self) -> Viterbi {
+            self.weight
+        }
+    }
+
+    fn prob(p: u32) -> Viterbi {
+        Viterbi(p * (VITERBI_ONE / 100))
+    }
+
+    #[test]
+    fn find_path_prefers_the_highest_weight_under_a_max_product_semiring() {
+        // 0 --a(p=0.2)--> 2 (final): total probability 0.2
+        // 0 --b(p=0.5)--> 1 --c(p=0.6)--> 2 (final): total probability 0.3 (preferred)
+        let mut automaton: TreeAutomation<ProbSymbol> = TreeAutomation::new();
+        automaton.add_initial_state(0, Viterbi::one());
+        automaton.add_transition(
+            ProbSymbol { id: b'a', weight: prob(20) },
+            StatePair::Single(0),
+            2,
+        );
+        automaton.add_transition(
+            ProbSymbol { id: b'b', weight: prob(50) },
+            StatePair::Single(0),
+            1,
+        );
+        automaton.add_transition(
+            ProbSymbol { id: b'c', weight: prob(60) },
+            StatePair::Single(1),
+            2,
+        );
+        automaton.set_final_state(2);
+
+        let best = automaton.find_path().expect("reachable");
+        assert_eq!(best.weight.0, prob(30).0);
+    }
+
+    // chunk0-6: on-disk save/load, including a memory-mapped variant, and
+    // graceful handling of a truncated/corrupt file.
+    fn sample_automaton() -> TreeAutomation<TestSymbol> {
+        let mut automaton = TreeAutomation::new();
+        automaton.add_initial_state(0, Tropical(1));
+        automaton.add_transition(sym(b'a', 2), StatePair::Single(0), 1);
+        automaton.add_transition(sym(b'b', 4), StatePair::Single(0), 1);
+        automaton.set_final_state(1);
+        automaton
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("tree_automata_test_{name}_{}.bin", std::process::id()))
+    }
+
+    #[test]
+    fn save_and_load_roundtrips_an_automaton() {
+        let automaton = sample_automaton();
+        let path = temp_path("roundtrip");
+        automaton.save(&path).expect("save succeeds");
+
+        let loaded: TreeAutomation<TestSymbol> = TreeAutomation::load(&path).expect("load succeeds");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.find_path().unwrap().weight.0, automaton.find_path().unwrap().weight.0);
+    }
+
+    #[test]
+    fn mapped_automaton_decodes_the_saved_transitions_and_final_state() {
+        let automaton = sample_automaton();
+        let path = temp_path("mmap");
+        automaton.save(&path).expect("save succeeds");
+
+        let mapped: MappedAutomation<TestSymbol> =
+            MappedAutomation::load(&path).expect("load succeeds");
+        let entries = mapped.transition_list(0).expect("decodes");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(mapped.final_state, Some(1));
+    }
+
+    #[test]
+    fn load_reports_an_error_instead_of_panicking_on_a_truncated_file() {
+        let automaton = sample_automaton();
+        let path = temp_path("truncated");
+        automaton.save(&path).expect("save succeeds");
+
+        let full = std::fs::read(&path).expect("read back");
+        std::fs::write(&path, &full[..full.len() / 2]).expect("write truncated");
+
+        let result: io::Result<TreeAutomation<TestSymbol>> = TreeAutomation::load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_reports_an_error_when_only_the_transition_data_is_truncated() {
+        // Truncate just the tail so the header and offset table still
+        // decode cleanly, but the last transition entry's bytes (state 0's
+        // second entry) are cut short.
+        let automaton = sample_automaton();
+        let path = temp_path("truncated_transitions");
+        automaton.save(&path).expect("save succeeds");
+
+        let full = std::fs::read(&path).expect("read back");
+        std::fs::write(&path, &full[..full.len() - 2]).expect("write truncated");
+
+        let result: io::Result<TreeAutomation<TestSymbol>> = TreeAutomation::load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(result.is_err());
+    }
+}